@@ -4,7 +4,61 @@ use crate::{
 use geom::{Bounds, Circle, Distance, Line, Polygon, Pt2D};
 use glium::{uniform, Surface};
 
-const TRIANGLES_PER_CIRCLE: usize = 60;
+const MIN_TRIANGLES_PER_CIRCLE: usize = 8;
+const MAX_TRIANGLES_PER_CIRCLE: usize = 60;
+// Max allowed deviation between the tessellated polygon and the true circle, in screen pixels.
+const MAX_CIRCLE_ERROR_PX: f64 = 0.3;
+
+// How overlapping draws combine. Defaults to `Alpha`, which is what every draw call used
+// before this existed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    // The usual alpha-over compositing.
+    Alpha,
+    // Colors accumulate; useful for glowing heatmap-style overlays.
+    Add,
+    // Colors multiply with what's underneath; useful for darkening/tinting.
+    Multiply,
+    // No blending at all; the new color overwrites the old one.
+    Replace,
+}
+
+impl BlendMode {
+    fn to_glium(self) -> glium::Blend {
+        use glium::{BlendingFunction, LinearBlendingFactor};
+
+        match self {
+            BlendMode::Alpha => glium::Blend::alpha_blending(),
+            BlendMode::Add => glium::Blend {
+                color: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::SourceAlpha,
+                    destination: LinearBlendingFactor::One,
+                },
+                alpha: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::One,
+                    destination: LinearBlendingFactor::One,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            BlendMode::Multiply => glium::Blend {
+                color: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::DestinationColor,
+                    destination: LinearBlendingFactor::Zero,
+                },
+                alpha: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::DestinationAlpha,
+                    destination: LinearBlendingFactor::Zero,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            BlendMode::Replace => glium::Blend {
+                color: BlendingFunction::AlwaysReplace,
+                alpha: BlendingFunction::AlwaysReplace,
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+        }
+    }
+}
 
 type Uniforms<'a> = glium::uniforms::UniformsStorage<
     'a,
@@ -12,12 +66,327 @@ type Uniforms<'a> = glium::uniforms::UniformsStorage<
     glium::uniforms::UniformsStorage<'a, [f32; 3], glium::uniforms::EmptyUniforms>,
 >;
 
-pub struct GfxCtx<'a> {
+// Per-instance attributes for `GfxCtx::draw_instances`. The vertex shader applies this affine
+// transform (offset + rotation + uniform scale) and color tint on top of the base shape's own
+// vertices, before the usual camera transform/window uniforms.
+//
+// Named `instance_*` rather than `offset`/`color`/etc. because `obj.vertex_buffer` is a second
+// vertex source zipped in alongside this one, and it already has its own per-vertex `color`;
+// reusing that name here would make glium bind the `color` location twice (last-wins) and drop
+// the base shape's own colors.
+#[derive(Copy, Clone)]
+pub struct Instance {
+    pub instance_offset: [f32; 2],
+    pub instance_rotation: f32,
+    pub instance_scale: f32,
+    pub instance_color: [f32; 4],
+}
+glium::implement_vertex!(
+    Instance,
+    instance_offset,
+    instance_rotation,
+    instance_scale,
+    instance_color
+);
+
+// Vertex/fragment source for `Prerender::instanced_program`. Applies each instance's
+// offset/rotation/scale to the base shape's `position` attribute, then the usual camera
+// transform/window uniforms, and tints with the instance's color.
+const INSTANCED_VERTEX_SRC: &str = r#"
+#version 140
+in vec2 position;
+in vec4 color;
+in vec2 instance_offset;
+in float instance_rotation;
+in float instance_scale;
+in vec4 instance_color;
+out vec4 v_color;
+uniform vec3 transform;
+uniform vec2 window;
+void main() {
+    v_color = color * instance_color;
+    float c = cos(instance_rotation);
+    float s = sin(instance_rotation);
+    vec2 local = instance_scale * vec2(c * position.x - s * position.y, s * position.x + c * position.y);
+    vec2 world = local + instance_offset;
+    vec2 screen = (world + transform.xy) * transform.z;
+    gl_Position = vec4(screen.x / window.x * 2.0 - 1.0, 1.0 - screen.y / window.y * 2.0, 0.0, 1.0);
+}
+"#;
+const INSTANCED_FRAGMENT_SRC: &str = r#"
+#version 140
+in vec4 v_color;
+out vec4 out_color;
+void main() {
+    out_color = v_color;
+}
+"#;
+
+// Vertex/fragment source for `Prerender::affine_program`. Applies a 2x2 rotate/scale matrix and
+// a translation to the base shape's `position` attribute, on top of the usual camera
+// transform/window uniforms, and tints with `obj_tint`.
+const AFFINE_VERTEX_SRC: &str = r#"
+#version 140
+in vec2 position;
+in vec4 color;
+out vec4 v_tint;
+uniform vec3 transform;
+uniform vec2 window;
+uniform mat2 obj_transform;
+uniform vec2 obj_translate;
+uniform vec4 obj_tint;
+void main() {
+    v_tint = color * obj_tint;
+    vec2 world = obj_transform * position + obj_translate;
+    vec2 screen = (world + transform.xy) * transform.z;
+    gl_Position = vec4(screen.x / window.x * 2.0 - 1.0, 1.0 - screen.y / window.y * 2.0, 0.0, 1.0);
+}
+"#;
+const AFFINE_FRAGMENT_SRC: &str = r#"
+#version 140
+in vec4 v_tint;
+out vec4 out_color;
+void main() {
+    out_color = v_tint;
+}
+"#;
+
+// Describes how to replay an already-uploaded `Drawable` at an arbitrary orientation, so static
+// geometry (a vehicle body, an arrow head) can be tessellated once and reused under many
+// transforms instead of calling `make_polygons` again every frame.
+pub struct DrawParam {
+    pub dest: Pt2D,
+    pub rotation: f32,
+    pub scale: f32,
+    pub color: Option<Color>,
+}
+
+impl DrawParam {
+    pub fn new(dest: Pt2D) -> DrawParam {
+        DrawParam {
+            dest,
+            rotation: 0.0,
+            scale: 1.0,
+            color: None,
+        }
+    }
+
+    pub fn rotated(mut self, rotation: f32) -> DrawParam {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn scaled(mut self, scale: f32) -> DrawParam {
+        self.scale = scale;
+        self
+    }
+
+    pub fn tinted(mut self, color: Color) -> DrawParam {
+        self.color = Some(color);
+        self
+    }
+}
+
+pub type FontId = usize;
+
+// One independently-styled run of text within a string drawn via `GfxCtx::draw_text_fragments`.
+// Mixing several fragments lets a single label use more than one size/color/font without
+// stitching together multiple draw calls.
+pub struct TextFragment {
+    pub text: String,
+    pub color: Color,
+    pub font: FontId,
+    pub scale: f32,
+}
+
+// Turning `(font, scale, text)` into pixel coverage needs an actual font backend (freetype,
+// rusttype, ...). That's not this module's job, same split of responsibility as glyph_brush vs
+// rusttype/ab_glyph: the embedder supplies rasterization, and `GlyphAtlas` below only owns atlas
+// packing, the positioning cache, and the batched GPU draw.
+pub trait GlyphRasterizer {
+    fn rasterize(&self, font: FontId, scale: f32, text: &str) -> RasterizedRun;
+}
+
+// One glyph's pixel coverage (8-bit alpha) plus its placement relative to the run's origin.
+pub struct RasterizedGlyph {
+    pub offset: (f32, f32),
+    pub width: u32,
+    pub height: u32,
+    pub coverage: Vec<u8>,
+}
+
+pub struct RasterizedRun {
+    pub glyphs: Vec<RasterizedGlyph>,
+    // How far the cursor should advance before laying out the next fragment.
+    pub advance: f32,
+}
+
+#[derive(Clone, Copy)]
+struct CachedQuad {
+    offset: (f32, f32),
+    size: (f32, f32),
+    uv_origin: (f32, f32),
+    uv_size: (f32, f32),
+}
+
+#[derive(Clone, Copy)]
+struct TextVertex {
+    pos: [f32; 2],
+    uv: [f32; 2],
+    tint: [f32; 4],
+}
+glium::implement_vertex!(TextVertex, pos, uv, tint);
+
+const TEXT_VERTEX_SRC: &str = r#"
+#version 140
+in vec2 pos;
+in vec2 uv;
+in vec4 tint;
+out vec2 v_uv;
+out vec4 v_tint;
+uniform vec2 window;
+void main() {
+    v_uv = uv;
+    v_tint = tint;
+    vec2 clip = vec2(pos.x / window.x * 2.0 - 1.0, 1.0 - pos.y / window.y * 2.0);
+    gl_Position = vec4(clip, 0.0, 1.0);
+}
+"#;
+const TEXT_FRAGMENT_SRC: &str = r#"
+#version 140
+in vec2 v_uv;
+in vec4 v_tint;
+out vec4 out_color;
+uniform sampler2D atlas;
+void main() {
+    float coverage = texture(atlas, v_uv).r;
+    out_color = vec4(v_tint.rgb, v_tint.a * coverage);
+}
+"#;
+
+// A GPU glyph cache: a shared texture atlas plus a cache of already-laid-out runs, keyed by
+// `(font, scale, text)`. Looking up a previously-seen run costs a hashmap lookup instead of a
+// re-rasterize and re-upload.
+pub struct GlyphAtlas {
+    texture: glium::texture::Texture2d,
+    cursor: (u32, u32),
+    row_height: u32,
+    cache: std::collections::HashMap<(FontId, u32, String), (Vec<CachedQuad>, f32)>,
+}
+
+impl GlyphAtlas {
+    pub fn new(prerender: &Prerender, size: u32) -> GlyphAtlas {
+        GlyphAtlas {
+            texture: glium::texture::Texture2d::empty_with_format(
+                prerender.display,
+                glium::texture::UncompressedFloatFormat::U8,
+                glium::texture::MipmapsOption::NoMipmap,
+                size,
+                size,
+            )
+            .unwrap(),
+            cursor: (0, 0),
+            row_height: 0,
+            cache: std::collections::HashMap::new(),
+        }
+    }
+
+    // Bump-allocates a `width` x `height` box in the atlas, wrapping to a new row when the
+    // current one's full. Doesn't bother reclaiming space; for a session with enough distinct
+    // `(font, scale, text)` runs to fill the whole texture, returns `None` instead of handing
+    // out a box that runs past the texture's bottom edge.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let size = self.texture.width();
+        if self.cursor.0 + width > size {
+            self.cursor.0 = 0;
+            self.cursor.1 += self.row_height;
+            self.row_height = 0;
+        }
+        if self.cursor.1 + height > self.texture.height() {
+            return None;
+        }
+        let pos = self.cursor;
+        self.cursor.0 += width;
+        self.row_height = self.row_height.max(height);
+        Some(pos)
+    }
+
+    // Returns the cached quads (and total advance) for `fragment`, rasterizing and uploading any
+    // glyphs not already in the atlas. `num_new_uploads` only grows on those cache misses.
+    fn layout(
+        &mut self,
+        num_new_uploads: &mut usize,
+        rasterizer: &dyn GlyphRasterizer,
+        fragment: &TextFragment,
+    ) -> (Vec<CachedQuad>, f32) {
+        let key = (fragment.font, fragment.scale.to_bits(), fragment.text.clone());
+        if let Some(cached) = self.cache.get(&key) {
+            return cached.clone();
+        }
+
+        let atlas_size = self.texture.width() as f32;
+        let run = rasterizer.rasterize(fragment.font, fragment.scale, &fragment.text);
+        let mut quads = Vec::new();
+        for glyph in run.glyphs {
+            let (atlas_x, atlas_y) = match self.allocate(glyph.width, glyph.height) {
+                Some(pos) => pos,
+                None => {
+                    // The atlas is full. Skip this glyph (it'll render as a gap) rather than
+                    // handing `texture.write` a Rect that runs past the texture's bottom edge.
+                    eprintln!(
+                        "GlyphAtlas is full; dropping a {}x{} glyph from {:?}",
+                        glyph.width, glyph.height, fragment.text
+                    );
+                    continue;
+                }
+            };
+            self.texture.write(
+                glium::Rect {
+                    left: atlas_x,
+                    bottom: atlas_y,
+                    width: glyph.width,
+                    height: glyph.height,
+                },
+                glium::texture::RawImage2d {
+                    data: std::borrow::Cow::Owned(glyph.coverage),
+                    width: glyph.width,
+                    height: glyph.height,
+                    format: glium::texture::ClientFormat::U8,
+                },
+            );
+            *num_new_uploads += 1;
+
+            quads.push(CachedQuad {
+                offset: glyph.offset,
+                size: (glyph.width as f32, glyph.height as f32),
+                uv_origin: (atlas_x as f32 / atlas_size, atlas_y as f32 / atlas_size),
+                uv_size: (
+                    glyph.width as f32 / atlas_size,
+                    glyph.height as f32 / atlas_size,
+                ),
+            });
+        }
+
+        let result = (quads, run.advance);
+        self.cache.insert(key, result.clone());
+        result
+    }
+}
+
+// Generic over the render target so the same drawing code can go to the screen's `glium::Frame`
+// or to an offscreen `glium::framebuffer::SimpleFrameBuffer` (see `Prerender::new_render_target`).
+pub struct GfxCtx<'a, S: Surface = glium::Frame> {
     display: &'a glium::Display,
-    target: &'a mut glium::Frame,
+    target: &'a mut S,
     program: &'a glium::Program,
     uniforms: Uniforms<'a>,
+    // Mirrors whatever `transform`/`window` is currently baked into `uniforms`, so draw paths
+    // that need a standalone `Uniforms` value (like `redraw_with`) can still honor `fork`/
+    // `fork_screenspace`/`unfork` instead of reading the raw, un-forked camera off `canvas`.
+    current_transform: [f32; 3],
+    current_window: [f32; 2],
     params: glium::DrawParameters<'a>,
+    blend_mode: BlendMode,
 
     // TODO Don't be pub. Delegate everything.
     pub canvas: &'a Canvas,
@@ -26,21 +395,23 @@ pub struct GfxCtx<'a> {
     pub num_draw_calls: usize,
 }
 
-impl<'a> GfxCtx<'a> {
+impl<'a, S: Surface> GfxCtx<'a, S> {
     pub fn new(
         canvas: &'a Canvas,
         display: &'a glium::Display,
-        target: &'a mut glium::Frame,
+        target: &'a mut S,
         program: &'a glium::Program,
-    ) -> GfxCtx<'a> {
+    ) -> GfxCtx<'a, S> {
         let params = glium::DrawParameters {
             blend: glium::Blend::alpha_blending(),
             ..Default::default()
         };
 
+        let current_transform = [canvas.cam_x as f32, canvas.cam_y as f32, canvas.cam_zoom as f32];
+        let current_window = [canvas.window_width as f32, canvas.window_height as f32];
         let uniforms = uniform! {
-            transform: [canvas.cam_x as f32, canvas.cam_y as f32, canvas.cam_zoom as f32],
-            window: [canvas.window_width as f32, canvas.window_height as f32],
+            transform: current_transform,
+            window: current_window,
         };
 
         GfxCtx {
@@ -49,12 +420,33 @@ impl<'a> GfxCtx<'a> {
             target,
             program,
             uniforms,
+            current_transform,
+            current_window,
             params,
+            blend_mode: BlendMode::Alpha,
             num_new_uploads: 0,
             num_draw_calls: 0,
         }
     }
 
+    // Returns the previous mode, so callers that only want to change the blend mode for a few
+    // draw calls can restore it afterwards: `let prev = gfx.set_blend_mode(BlendMode::Add); ...
+    // gfx.set_blend_mode(prev);`
+    pub fn set_blend_mode(&mut self, mode: BlendMode) -> BlendMode {
+        let prev = self.blend_mode;
+        self.blend_mode = mode;
+        self.params.blend = mode.to_glium();
+        prev
+    }
+
+    // Scoped variant of `set_blend_mode`: runs `draw` with `mode` active, then always restores
+    // whatever mode was active before, so callers can't forget to undo it.
+    pub fn with_blend_mode(&mut self, mode: BlendMode, draw: impl FnOnce(&mut GfxCtx<'a, S>)) {
+        let prev = self.set_blend_mode(mode);
+        draw(self);
+        self.set_blend_mode(prev);
+    }
+
     // Up to the caller to call unfork()!
     // TODO Canvas doesn't understand this change, so things like text drawing that use
     // map_to_screen will just be confusing.
@@ -63,23 +455,33 @@ impl<'a> GfxCtx<'a> {
         let cam_x = (top_left_map.x() * zoom) - top_left_screen.x;
         let cam_y = (top_left_map.y() * zoom) - top_left_screen.y;
 
+        self.current_transform = [cam_x as f32, cam_y as f32, zoom as f32];
+        self.current_window = [self.canvas.window_width as f32, self.canvas.window_height as f32];
         self.uniforms = uniform! {
-            transform: [cam_x as f32, cam_y as f32, zoom as f32],
-            window: [self.canvas.window_width as f32, self.canvas.window_height as f32],
+            transform: self.current_transform,
+            window: self.current_window,
         };
     }
 
     pub fn fork_screenspace(&mut self) {
+        self.current_transform = [0.0, 0.0, 1.0];
+        self.current_window = [self.canvas.window_width as f32, self.canvas.window_height as f32];
         self.uniforms = uniform! {
-            transform: [0.0, 0.0, 1.0],
-            window: [self.canvas.window_width as f32, self.canvas.window_height as f32],
+            transform: self.current_transform,
+            window: self.current_window,
         };
     }
 
     pub fn unfork(&mut self) {
+        self.current_transform = [
+            self.canvas.cam_x as f32,
+            self.canvas.cam_y as f32,
+            self.canvas.cam_zoom as f32,
+        ];
+        self.current_window = [self.canvas.window_width as f32, self.canvas.window_height as f32];
         self.uniforms = uniform! {
-            transform: [self.canvas.cam_x as f32, self.canvas.cam_y as f32, self.canvas.cam_zoom as f32],
-            window: [self.canvas.window_width as f32, self.canvas.window_height as f32],
+            transform: self.current_transform,
+            window: self.current_window,
         };
     }
 
@@ -96,16 +498,14 @@ impl<'a> GfxCtx<'a> {
     }
 
     pub fn draw_rounded_line(&mut self, color: Color, thickness: Distance, line: &Line) {
+        let circle1 = Circle::new(line.pt1(), thickness / 2.0);
+        let circle2 = Circle::new(line.pt2(), thickness / 2.0);
+        let n1 = self.circle_triangle_count(&circle1);
+        let n2 = self.circle_triangle_count(&circle2);
         self.draw_polygon_batch(vec![
             (color, &line.make_polygons(thickness)),
-            (
-                color,
-                &Circle::new(line.pt1(), thickness / 2.0).to_polygon(TRIANGLES_PER_CIRCLE),
-            ),
-            (
-                color,
-                &Circle::new(line.pt2(), thickness / 2.0).to_polygon(TRIANGLES_PER_CIRCLE),
-            ),
+            (color, &circle1.to_polygon(n1)),
+            (color, &circle2.to_polygon(n2)),
         ]);
     }
 
@@ -115,7 +515,28 @@ impl<'a> GfxCtx<'a> {
     }
 
     pub fn draw_circle(&mut self, color: Color, circle: &Circle) {
-        self.draw_polygon(color, &circle.to_polygon(TRIANGLES_PER_CIRCLE));
+        let n = self.circle_triangle_count(circle);
+        self.draw_polygon(color, &circle.to_polygon(n));
+    }
+
+    // For callers that need a specific triangle count (matching another shape exactly, say)
+    // instead of the zoom-adaptive default that `draw_circle` picks.
+    pub fn draw_circle_with_precision(&mut self, color: Color, circle: &Circle, triangles: usize) {
+        self.draw_polygon(color, &circle.to_polygon(triangles));
+    }
+
+    // Picks a triangle count so the tessellated polygon's max deviation from the true circle
+    // stays under MAX_CIRCLE_ERROR_PX on screen, clamped to
+    // [MIN_TRIANGLES_PER_CIRCLE, MAX_TRIANGLES_PER_CIRCLE]. Past the cap (a screen radius of
+    // about 219 pixels) the deviation grows beyond MAX_CIRCLE_ERROR_PX.
+    fn circle_triangle_count(&self, circle: &Circle) -> usize {
+        let screen_radius = circle.radius.inner_meters() * self.canvas.cam_zoom;
+        if screen_radius <= 0.0 {
+            return MIN_TRIANGLES_PER_CIRCLE;
+        }
+        let cos_half_angle = (1.0 - MAX_CIRCLE_ERROR_PX / screen_radius).max(-1.0);
+        let n = (std::f64::consts::PI / cos_half_angle.acos()).ceil() as usize;
+        n.max(MIN_TRIANGLES_PER_CIRCLE).min(MAX_TRIANGLES_PER_CIRCLE)
     }
 
     pub fn draw_polygon(&mut self, color: Color, poly: &Polygon) {
@@ -149,6 +570,175 @@ impl<'a> GfxCtx<'a> {
         self.num_draw_calls += 1;
     }
 
+    // Draws many copies of the same base shape in a single draw call, instead of uploading and
+    // drawing each one separately. Meant for things like cars or pedestrians, where thousands of
+    // agents share identical geometry and only differ by position/rotation/scale/color.
+    //
+    // `program` must be built from `Prerender::instanced_program`; it applies each `Instance`'s
+    // offset/rotation/scale/color on top of `obj`'s own vertices before the usual camera
+    // transform. The base program used by `redraw` doesn't declare those attributes.
+    pub fn draw_instances(&mut self, obj: &Drawable, instances: &[Instance], program: &glium::Program) {
+        if instances.is_empty() {
+            return;
+        }
+
+        let instance_buffer = glium::VertexBuffer::new(self.display, instances).unwrap();
+        match instance_buffer.per_instance() {
+            Ok(per_instance) => {
+                self.target
+                    .draw(
+                        (&obj.vertex_buffer, per_instance),
+                        &obj.index_buffer,
+                        program,
+                        &self.uniforms,
+                        &self.params,
+                    )
+                    .unwrap();
+                self.num_draw_calls += 1;
+            }
+            Err(_) => {
+                // The driver doesn't support instanced arrays at all, so a 1-instance buffer
+                // would hit the same error. Instead, broadcast each instance's attributes into a
+                // regular (non-instanced) per-vertex buffer matching `obj`'s vertex count, and
+                // issue one ordinary draw call per instance; `program` reads the same attribute
+                // names either way.
+                let vertex_count = obj.vertex_buffer.len();
+                for instance in instances {
+                    let per_vertex = vec![*instance; vertex_count];
+                    let per_vertex_buffer = glium::VertexBuffer::new(self.display, &per_vertex).unwrap();
+                    self.target
+                        .draw(
+                            (&obj.vertex_buffer, &per_vertex_buffer),
+                            &obj.index_buffer,
+                            program,
+                            &self.uniforms,
+                            &self.params,
+                        )
+                        .unwrap();
+                    self.num_draw_calls += 1;
+                }
+            }
+        }
+    }
+
+    // Like `redraw`, but composes a rotation/scale/translation/tint on top of the camera
+    // transform rather than drawing `obj` at its original orientation and color.
+    //
+    // `program` must be built from `Prerender::affine_program`; it's the one that actually
+    // declares `obj_transform`/`obj_translate`/`obj_tint`. The base program used by `redraw`
+    // doesn't know about them, and glium silently ignores uniforms a program doesn't declare.
+    pub fn redraw_with(&mut self, obj: &Drawable, param: DrawParam, program: &glium::Program) {
+        let cos = param.rotation.cos();
+        let sin = param.rotation.sin();
+        let s = param.scale;
+        // glium uploads `[[f32; 2]; 2]` column-major (no transpose), so this literal's two
+        // arrays are the matrix's columns: (cos*s, sin*s) and (-sin*s, cos*s), i.e. R(+rotation)
+        // — the same counter-clockwise convention `INSTANCED_VERTEX_SRC` uses for `Instance`.
+        let obj_transform = [[cos * s, sin * s], [-sin * s, cos * s]];
+        let obj_tint = param.color.map(|c| c.0).unwrap_or([1.0, 1.0, 1.0, 1.0]);
+
+        let uniforms = uniform! {
+            transform: self.current_transform,
+            window: self.current_window,
+            obj_transform: obj_transform,
+            obj_translate: [param.dest.x() as f32, param.dest.y() as f32],
+            obj_tint: obj_tint,
+        };
+
+        self.target
+            .draw(
+                &obj.vertex_buffer,
+                &obj.index_buffer,
+                program,
+                &uniforms,
+                &self.params,
+            )
+            .unwrap();
+        self.num_draw_calls += 1;
+    }
+
+    // Draws `fragments` as one label at `pt` (screen-space top-left), mixing each fragment's own
+    // font/scale/color. Glyphs are rasterized and uploaded into `atlas` only the first time a
+    // given `(font, scale, text)` run is seen; every call after that is a cache hit and costs one
+    // quad-batch draw call, no re-layout.
+    //
+    // `program` must be built from `Prerender::text_program`.
+    pub fn draw_text_fragments(
+        &mut self,
+        atlas: &mut GlyphAtlas,
+        rasterizer: &dyn GlyphRasterizer,
+        fragments: &[TextFragment],
+        pt: ScreenPt,
+        program: &glium::Program,
+    ) {
+        let mut vertices = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut cursor_x = pt.x as f32;
+        let cursor_y = pt.y as f32;
+
+        for fragment in fragments {
+            let (quads, advance) = atlas.layout(&mut self.num_new_uploads, rasterizer, fragment);
+            let tint = fragment.color.0;
+            for quad in &quads {
+                let base = vertices.len() as u32;
+                let x0 = cursor_x + quad.offset.0;
+                let y0 = cursor_y + quad.offset.1;
+                let x1 = x0 + quad.size.0;
+                let y1 = y0 + quad.size.1;
+                let (u0, v0) = quad.uv_origin;
+                let (du, dv) = quad.uv_size;
+                vertices.push(TextVertex {
+                    pos: [x0, y0],
+                    uv: [u0, v0],
+                    tint,
+                });
+                vertices.push(TextVertex {
+                    pos: [x1, y0],
+                    uv: [u0 + du, v0],
+                    tint,
+                });
+                vertices.push(TextVertex {
+                    pos: [x1, y1],
+                    uv: [u0 + du, v0 + dv],
+                    tint,
+                });
+                vertices.push(TextVertex {
+                    pos: [x0, y1],
+                    uv: [u0, v0 + dv],
+                    tint,
+                });
+                indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+            cursor_x += advance;
+        }
+
+        if vertices.is_empty() {
+            return;
+        }
+
+        let vertex_buffer = glium::VertexBuffer::new(self.display, &vertices).unwrap();
+        let index_buffer = glium::IndexBuffer::new(
+            self.display,
+            glium::index::PrimitiveType::TrianglesList,
+            &indices,
+        )
+        .unwrap();
+        let uniforms = uniform! {
+            window: [self.canvas.window_width as f32, self.canvas.window_height as f32],
+            atlas: atlas.texture.sampled(),
+        };
+        self.target
+            .draw(&vertex_buffer, &index_buffer, program, &uniforms, &self.params)
+            .unwrap();
+        self.num_draw_calls += 1;
+    }
+}
+
+// `Canvas`'s methods below take a concrete `&mut GfxCtx<'_, glium::Frame>` (it was never made
+// generic over the render target), so these forwards only typecheck for the default `S`. Code
+// that draws to an offscreen target (see `Prerender::new_render_target`) should use the drawing
+// primitives above instead of these.
+impl<'a> GfxCtx<'a, glium::Frame> {
     // Forwarded canvas stuff.
     pub fn draw_blocking_text(
         &mut self,
@@ -179,3 +769,36 @@ impl<'a> GfxCtx<'a> {
         self.canvas.get_cursor_in_map_space()
     }
 }
+
+impl Prerender {
+    // Draw the map (or any `GfxCtx` content) into a texture instead of the backbuffer, for
+    // minimaps, thumbnails, and screenshots. Build a `GfxCtx` on top of the returned texture
+    // with `render_target_framebuffer` plus `GfxCtx::new`, draw into it, then read it back.
+    // Nothing here draws the texture itself onto another target: `redraw` only replays a
+    // `Drawable`'s colored vertices.
+    pub fn new_render_target(&self, width: u32, height: u32) -> glium::texture::Texture2d {
+        glium::texture::Texture2d::empty(self.display, width, height).unwrap()
+    }
+
+    pub fn render_target_framebuffer<'a>(
+        &'a self,
+        texture: &'a glium::texture::Texture2d,
+    ) -> glium::framebuffer::SimpleFrameBuffer<'a> {
+        glium::framebuffer::SimpleFrameBuffer::new(self.display, texture).unwrap()
+    }
+
+    // Build once (it's not cheap) and pass to every `GfxCtx::draw_instances` call.
+    pub fn instanced_program(&self) -> glium::Program {
+        glium::Program::from_source(self.display, INSTANCED_VERTEX_SRC, INSTANCED_FRAGMENT_SRC, None).unwrap()
+    }
+
+    // Build once and pass to every `GfxCtx::redraw_with` call.
+    pub fn affine_program(&self) -> glium::Program {
+        glium::Program::from_source(self.display, AFFINE_VERTEX_SRC, AFFINE_FRAGMENT_SRC, None).unwrap()
+    }
+
+    // Build once and pass to every `GfxCtx::draw_text_fragments` call.
+    pub fn text_program(&self) -> glium::Program {
+        glium::Program::from_source(self.display, TEXT_VERTEX_SRC, TEXT_FRAGMENT_SRC, None).unwrap()
+    }
+}